@@ -1,9 +1,21 @@
 use bpaf::Bpaf;
+use serde::Serialize;
+use std::fmt;
 use std::io::{self, Write};
 use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
 use std::sync::mpsc::{channel, Sender};
-use tokio::net::TcpStream; // Note this is Asynchronous !
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpStream, UdpSocket}; // Note this is Asynchronous !
+use tokio::sync::Semaphore; // caps in-flight connection attempts
 use tokio::task; // "Tasks are green threads in the Tokio system"
+use tokio::time; // for the connect timeout
+use tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{ClientConfig, DigitallySignedStruct, SignatureScheme};
+use tokio_rustls::TlsConnector;
+use x509_parser::prelude::FromDer;
 
 // Credit : Tensor Programming - https://www.youtube.com/watch?v=RhFZxkxkeIc&t=705s
 
@@ -15,14 +27,27 @@ use tokio::task; // "Tasks are green threads in the Tokio system"
 const MAX: u16 = 65535;
 
 // Address fallback.
-const IPFALLBACK: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+const ADDRESS_FALLBACK: &str = "127.0.0.1";
+
+// Fallback connect timeout, in milliseconds.
+const TIMEOUT_FALLBACK_MS: u64 = 3000;
+
+// Fallback number of connections allowed in flight at once.
+const CONCURRENCY_FALLBACK: usize = 1000;
+
+// Fallback number of retries for a transient (non-refused) connect failure.
+const RETRIES_FALLBACK: u32 = 1;
+
+// Starting backoff between retries; doubles on each subsequent attempt.
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
 
 // get cli arguments
 #[derive(Debug, Clone, Bpaf)]
 #[bpaf(options)]
 pub struct Arguments {
-    #[bpaf(short, long, fallback(IPFALLBACK))]
-    pub address: IpAddr,
+    // an IP address, or a hostname to resolve via DNS
+    #[bpaf(short, long, fallback(ADDRESS_FALLBACK.to_string()))]
+    pub address: String,
 
     // low port
     #[bpaf(
@@ -41,6 +66,69 @@ pub struct Arguments {
         fallback(MAX)
     )]
     pub end_port: u16,
+
+    // connect timeout, in milliseconds
+    #[bpaf(long("timeout"), short('t'), fallback(TIMEOUT_FALLBACK_MS))]
+    pub timeout_ms: u64,
+
+    // max number of in-flight connection attempts
+    #[bpaf(long("concurrency"), short('c'), fallback(CONCURRENCY_FALLBACK))]
+    pub concurrency: usize,
+
+    // tcp (connect scan) or udp (datagram probe)
+    #[bpaf(long("protocol"), short('p'), fallback(Protocol::Tcp))]
+    pub protocol: Protocol,
+
+    // attempt a TLS handshake on open ports and report the negotiated
+    // protocol / certificate
+    #[bpaf(long("tls"))]
+    pub tls: bool,
+
+    // text (default) or json
+    #[bpaf(long("output"), short('o'), fallback(OutputFormat::Text))]
+    pub output: OutputFormat,
+
+    // retries for a transient (non-refused) connect failure
+    #[bpaf(long("retries"), fallback(RETRIES_FALLBACK))]
+    pub retries: u32,
+}
+
+// How to print scan results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            other => Err(format!("unknown output format '{}', expected text or json", other)),
+        }
+    }
+}
+
+// Which transport to probe ports with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "tcp" => Ok(Protocol::Tcp),
+            "udp" => Ok(Protocol::Udp),
+            other => Err(format!("unknown protocol '{}', expected tcp or udp", other)),
+        }
+    }
 }
 
 // Borrow the input!
@@ -53,16 +141,287 @@ fn end_port_guard(input: &u16) -> bool {
     *input < MAX
 }
 
-// scan ports
-async fn scan(tx: Sender<u16>, port: u16, addr: IpAddr) {
-    match TcpStream::connect(format!("{}:{}", addr, port)).await {
-        Ok(_) => {
-            println!(".");
-            io::stdout().flush().unwrap();
-            tx.send(port).unwrap();
+// Resolve the `--address` argument to one or more IPs. Accepts a literal IP
+// as-is; otherwise treats it as a hostname and resolves it asynchronously
+// (Tokio runs `getaddrinfo` on its blocking thread pool under the hood).
+async fn resolve_target(target: &str) -> io::Result<Vec<IpAddr>> {
+    if let Ok(ip) = target.parse::<IpAddr>() {
+        return Ok(vec![ip]);
+    }
+    let addrs: Vec<IpAddr> = tokio::net::lookup_host((target, 0))
+        .await?
+        .map(|socket_addr| socket_addr.ip())
+        .collect();
+    Ok(addrs)
+}
+
+// The outcome of probing a port that was not found closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PortState {
+    // Connection was accepted, or a UDP datagram got a reply.
+    Open,
+    // Neither accepted nor refused before the timeout elapsed,
+    // e.g. a firewall silently dropping the SYN.
+    Filtered,
+    // UDP only: no reply and no ICMP unreachable before the timeout, so we
+    // can't tell open and filtered apart.
+    OpenFiltered,
+}
+
+impl fmt::Display for PortState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortState::Open => write!(f, "open"),
+            PortState::Filtered => write!(f, "filtered"),
+            PortState::OpenFiltered => write!(f, "open|filtered"),
+        }
+    }
+}
+
+// Probe a single port over TCP by attempting a connect. Transient connect
+// errors (anything short of an explicit refusal) are retried with exponential
+// backoff so a flaky network or a burst of ephemeral errors under heavy
+// concurrency doesn't get misreported as a closed port; a timeout is not
+// retried and is reported as filtered. On success, hands
+// back the still-open stream so callers (e.g. the TLS probe) can reuse it
+// instead of reconnecting.
+async fn tcp_probe(
+    addr: IpAddr,
+    port: u16,
+    timeout: Duration,
+    retries: u32,
+) -> Option<(PortState, Option<TcpStream>)> {
+    let mut backoff = RETRY_BACKOFF;
+    for attempt in 0..=retries {
+        match time::timeout(timeout, TcpStream::connect((addr, port))).await {
+            Ok(Ok(stream)) => return Some((PortState::Open, Some(stream))),
+            // Connection actively refused: definitively closed, don't retry.
+            Ok(Err(e)) if e.kind() == io::ErrorKind::ConnectionRefused => return None,
+            // Some other transient connect error (e.g. ETIMEDOUT, a
+            // resource limit): retry unless we're out of attempts.
+            Ok(Err(_)) if attempt < retries => {
+                time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Ok(Err(_)) => return None,
+            // Timed out without a connect or a refusal: intentionally not
+            // retried, unlike the transient-error branch above. A timeout
+            // against a filtered host is the common case across a whole
+            // port range, and retrying it would multiply the cost of a scan
+            // by `retries` for no real benefit; it's also never reported
+            // as open, so it doesn't pollute scan output (see `scan`).
+            Err(_) => return Some((PortState::Filtered, None)),
+        }
+    }
+    unreachable!("loop always returns before exhausting its range")
+}
+
+// Probe a single port over UDP: send an empty datagram and see whether
+// anything comes back within the timeout, or whether the kernel surfaces an
+// ICMP port-unreachable as a `ConnectionRefused`-class error.
+async fn udp_probe(addr: IpAddr, port: u16, timeout: Duration) -> Option<PortState> {
+    let bind_addr: IpAddr = match addr {
+        IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        IpAddr::V6(_) => std::net::Ipv6Addr::UNSPECIFIED.into(),
+    };
+    let socket = UdpSocket::bind((bind_addr, 0)).await.ok()?;
+    socket.connect((addr, port)).await.ok()?;
+    socket.send(&[]).await.ok()?;
+
+    let mut buf = [0u8; 512];
+    match time::timeout(timeout, socket.recv(&mut buf)).await {
+        Ok(Ok(_)) => Some(PortState::Open),
+        // ICMP port-unreachable surfaces here as connection refused: closed.
+        Ok(Err(_)) => None,
+        // No reply and no unreachable before the timeout: can't tell open
+        // from filtered.
+        Err(_) => Some(PortState::OpenFiltered),
+    }
+}
+
+// What a TLS probe found on an open port.
+#[derive(Debug, Clone, Serialize)]
+struct TlsInfo {
+    // ALPN protocol the server selected, e.g. "h2".
+    alpn: Option<String>,
+    // Subject CN / first SAN of the presented certificate.
+    subject: Option<String>,
+}
+
+// Accepts any certificate the server presents. We're fingerprinting, not
+// establishing trust, so the usual chain-of-trust checks don't apply here;
+// we still hand back whatever certificate was presented so the caller can
+// inspect it.
+#[derive(Debug)]
+struct AcceptAnyCert;
+
+impl ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        // Accept whatever the server offers; we never actually check the signature.
+        vec![
+            SignatureScheme::RSA_PKCS1_SHA1,
+            SignatureScheme::ECDSA_SHA1_Legacy,
+            SignatureScheme::RSA_PKCS1_SHA256,
+            SignatureScheme::RSA_PKCS1_SHA384,
+            SignatureScheme::RSA_PKCS1_SHA512,
+            SignatureScheme::ECDSA_NISTP256_SHA256,
+            SignatureScheme::ECDSA_NISTP384_SHA384,
+            SignatureScheme::ECDSA_NISTP521_SHA512,
+            SignatureScheme::RSA_PSS_SHA256,
+            SignatureScheme::RSA_PSS_SHA384,
+            SignatureScheme::RSA_PSS_SHA512,
+            SignatureScheme::ED25519,
+            SignatureScheme::ED448,
+        ]
+    }
+}
+
+// Pull a human-readable subject out of a DER certificate, preferring the
+// subject CN and falling back to the first SAN entry.
+fn extract_subject(cert: &CertificateDer<'_>) -> Option<String> {
+    let (_, parsed) = x509_parser::certificate::X509Certificate::from_der(cert.as_ref()).ok()?;
+    if let Some(cn) = parsed.subject().iter_common_name().next() {
+        if let Ok(cn) = cn.as_str() {
+            return Some(cn.to_string());
         }
-        Err(_) => {}
     }
+    parsed
+        .subject_alternative_name()
+        .ok()
+        .flatten()
+        .and_then(|san| san.value.general_names.first().map(|n| n.to_string()))
+}
+
+// After a port is found open, attempt a TLS handshake on the already-open
+// stream to learn whether it speaks TLS, and if so, what ALPN protocol and
+// certificate it presents. Reuses the stream from `tcp_probe` rather than
+// reconnecting, so we don't double the connect load per open port or risk
+// the port behaving differently (or having closed) on a second connect.
+async fn probe_tls(addr: IpAddr, stream: TcpStream, timeout: Duration) -> Option<TlsInfo> {
+    let mut config = ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCert))
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    let connector = TlsConnector::from(Arc::new(config));
+
+    let server_name = ServerName::IpAddress(addr.into());
+    let tls_stream = time::timeout(timeout, connector.connect(server_name, stream))
+        .await
+        .ok()?
+        .ok()?;
+
+    let (_, session) = tls_stream.get_ref();
+    let alpn = session
+        .alpn_protocol()
+        .map(|p| String::from_utf8_lossy(p).into_owned());
+    let subject = session
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .and_then(extract_subject);
+
+    Some(TlsInfo { alpn, subject })
+}
+
+// The parameters shared by every port scanned in a run, as opposed to
+// per-task state (address, port, channel, permit).
+#[derive(Debug, Clone, Copy)]
+struct ScanConfig {
+    timeout: Duration,
+    protocol: Protocol,
+    tls: bool,
+    retries: u32,
+}
+
+// scan ports
+//
+// `permit` is already acquired by the caller before the task is spawned, so
+// we never have more than `concurrency` tasks (and therefore sockets) alive
+// at once, rather than just bounding the sockets within an unbounded flood
+// of spawned tasks.
+async fn scan(
+    tx: Sender<(IpAddr, u16, PortState, Option<TlsInfo>)>,
+    addr: IpAddr,
+    port: u16,
+    permit: tokio::sync::OwnedSemaphorePermit,
+    config: ScanConfig,
+) {
+    let _permit = permit;
+    let (state, stream) = match config.protocol {
+        Protocol::Tcp => match tcp_probe(addr, port, config.timeout, config.retries).await {
+            Some((state, stream)) => (Some(state), stream),
+            None => (None, None),
+        },
+        Protocol::Udp => (udp_probe(addr, port, config.timeout).await, None),
+    };
+    // Only a confirmed open port is worth reporting: filtered/open|filtered
+    // just means the timeout fired without an answer, and on a scan against
+    // a filtered host that's most of the port range.
+    if let Some(state) = state.filter(|s| *s == PortState::Open) {
+        // Progress indicator only, not a result: goes to stderr so
+        // `--output json` on stdout stays valid, parseable JSON.
+        eprint!(".");
+        io::stderr().flush().unwrap();
+        let tls_info = if config.tls {
+            match stream {
+                Some(stream) => probe_tls(addr, stream, config.timeout).await,
+                None => None,
+            }
+        } else {
+            None
+        };
+        tx.send((addr, port, state, tls_info)).unwrap();
+    }
+}
+
+// A confirmed-open port, as reported in `--output json`.
+#[derive(Debug, Serialize)]
+struct OpenPortRecord {
+    address: IpAddr,
+    port: u16,
+    state: String,
+    tls: Option<TlsInfo>,
+}
+
+// The full scan result, as reported in `--output json`.
+#[derive(Debug, Serialize)]
+struct ScanReport {
+    targets: Vec<IpAddr>,
+    start_port: u16,
+    end_port: u16,
+    timeout_ms: u64,
+    concurrency: usize,
+    protocol: String,
+    open_ports: Vec<OpenPortRecord>,
 }
 
 // Main
@@ -72,16 +431,38 @@ async fn main() {
     // creates a FUNCTION of the SAME NAME
     // collects values from the parser and puts them into the struct
     let opts: Arguments = arguments().run();
+    let config = ScanConfig {
+        timeout: Duration::from_millis(opts.timeout_ms),
+        protocol: opts.protocol,
+        tls: opts.tls,
+        retries: opts.retries,
+    };
+    // Bounds how many connection attempts are in flight at once, so a full
+    // 1-65535 scan doesn't exhaust the process's file-descriptor limit.
+    let limiter = Arc::new(Semaphore::new(opts.concurrency));
+
+    // Resolve the target once up front; a hostname may fan out to several IPs.
+    let targets = resolve_target(&opts.address).await.unwrap_or_else(|e| {
+        eprintln!("failed to resolve '{}': {}", opts.address, e);
+        std::process::exit(1);
+    });
 
     // Initialize the channel.
     let (tx, rx) = channel();
-    // Iterate through all of the ports (based on user input)
-    // so that we can spawn a single task for each.
+    // Iterate through every resolved address and all of its ports (based on
+    // user input) so that we can spawn a single task for each.
     // Much faster than before because it uses green threads instead of OS threads.
     // Tasks are green threads in the "Tokio system"
-    for i in opts.start_port..opts.end_port {
-        let tx = tx.clone();
-        task::spawn(async move { scan(tx, i, opts.address).await });
+    for addr in &targets {
+        for i in opts.start_port..opts.end_port {
+            // Acquire the permit here, before spawning, so an unbounded
+            // range doesn't also pile up an unbounded number of live tasks
+            // waiting on the semaphore inside `scan`.
+            let permit = limiter.clone().acquire_owned().await.unwrap();
+            let tx = tx.clone();
+            let addr = *addr;
+            task::spawn(async move { scan(tx, addr, i, permit, config).await });
+        }
     }
     let mut out = vec![];
     drop(tx);
@@ -90,11 +471,50 @@ async fn main() {
         out.push(p);
     }
 
-    println!("");
-    out.sort();
-    for v in out {
-        // Iterate through the outputs and print them out as being open.
-        println!("{} is open", v);
+    out.sort_by_key(|(addr, port, _, _)| (*addr, *port));
+
+    match opts.output {
+        OutputFormat::Text => {
+            println!();
+            for (addr, port, state, tls_info) in out {
+                // Iterate through the outputs and print them out with their
+                // state, labeled with which resolved address was scanned.
+                match tls_info {
+                    Some(TlsInfo { alpn, subject }) => println!(
+                        "{}:{} is {} (tls: alpn={}, subject={})",
+                        addr,
+                        port,
+                        state,
+                        alpn.as_deref().unwrap_or("-"),
+                        subject.as_deref().unwrap_or("-"),
+                    ),
+                    None => println!("{}:{} is {}", addr, port, state),
+                }
+            }
+        }
+        OutputFormat::Json => {
+            let report = ScanReport {
+                targets,
+                start_port: opts.start_port,
+                end_port: opts.end_port,
+                timeout_ms: opts.timeout_ms,
+                concurrency: opts.concurrency,
+                protocol: match opts.protocol {
+                    Protocol::Tcp => "tcp".to_string(),
+                    Protocol::Udp => "udp".to_string(),
+                },
+                open_ports: out
+                    .into_iter()
+                    .map(|(address, port, state, tls)| OpenPortRecord {
+                        address,
+                        port,
+                        state: state.to_string(),
+                        tls,
+                    })
+                    .collect(),
+            };
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        }
     }
 }
 
@@ -113,19 +533,42 @@ mod tests {
         let addr = listener.local_addr().unwrap();
 
         // Create a channel for testing
-        let (tx, _rx) = channel();
+        let (tx, rx) = channel();
+        let target = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
 
         // Try to connect using the scan function
         let result = task::spawn(scan(
             tx,
+            target,
             addr.port(),
-            // addr.ip(),
-            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            Arc::new(Semaphore::new(CONCURRENCY_FALLBACK))
+                .acquire_owned()
+                .await
+                .unwrap(),
+            ScanConfig {
+                timeout: Duration::from_millis(TIMEOUT_FALLBACK_MS),
+                protocol: Protocol::Tcp,
+                tls: false,
+                retries: RETRIES_FALLBACK,
+            },
         ))
         .await;
 
         // Ensure that the connection attempt was successful
         assert!(result.is_ok());
+
+        // And that the bound port was actually reported open over the channel.
+        let (reported_addr, reported_port, state, tls_info) = rx.recv().unwrap();
+        assert_eq!(reported_addr, target);
+        assert_eq!(reported_port, addr.port());
+        assert_eq!(state, PortState::Open);
+        assert!(tls_info.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_target_accepts_literal_ip() {
+        let addrs = resolve_target("127.0.0.1").await.unwrap();
+        assert_eq!(addrs, vec![IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))]);
     }
 }
 